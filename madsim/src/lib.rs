@@ -1,6 +1,7 @@
 use std::{future::Future, net::SocketAddr};
 
 mod context;
+pub mod fd;
 pub mod fs;
 pub mod net;
 pub mod rand;
@@ -62,6 +63,17 @@ impl Runtime {
         let _guard = crate::context::enter(self.handle());
         self.task.block_on(future)
     }
+
+    /// Restart a node that was previously [`Handle::kill`]ed and hand back a
+    /// fresh [`LocalHandle`] for it. [`fs::FileSystemRuntime`] keeps a
+    /// node's filesystem keyed by address across a kill, so whatever it had
+    /// durably synced before the crash is still there for the caller to
+    /// assert on. `task` and `net` aren't implemented in this tree yet, so
+    /// there's no per-node task/connection state here to tear down and
+    /// rebuild; that lands alongside those subsystems.
+    pub fn restart_node(&self, addr: SocketAddr) -> LocalHandle {
+        self.local_handle(addr)
+    }
 }
 
 #[derive(Clone)]
@@ -78,10 +90,22 @@ impl Handle {
         context::current().expect("no madsim context")
     }
 
+    /// Simulate a node crash: abort every task running on `addr` and lose
+    /// whatever filesystem writes were not fsync'd. Durably-synced disk
+    /// contents survive for a later [`Runtime::restart_node`].
+    ///
+    /// `fs.power_fail` must run *before* `task.kill`: aborting a task drops
+    /// its future, which runs the destructors of any `File` locals it
+    /// holds, and `File`'s `Drop` flushes unsynced writes with no
+    /// crash-awareness of its own. Discarding those writes first means
+    /// there's nothing left for that flush to persist.
+    ///
+    /// `net` isn't implemented in this tree yet, so a crashed node's open
+    /// connections aren't torn down here; wire in `self.net.kill(addr)` once
+    /// it lands.
     pub fn kill(&self, addr: SocketAddr) {
+        self.fs.power_fail(addr);
         self.task.kill(addr);
-        // self.net.kill(addr);
-        // self.fs.power_fail(addr);
     }
 
     pub fn local_handle(&self, addr: SocketAddr) -> LocalHandle {
@@ -1,131 +1,862 @@
-use crate::{rand::RandomHandle, task::TaskHandle, time::TimeHandle};
+use crate::{
+    fd::{AsSimFd, BorrowedSimFd, DescriptorTable, OwnedSimFd},
+    rand::RandomHandle,
+    time::TimeHandle,
+};
 use log::*;
 use std::{
     collections::HashMap,
-    io::{Error, ErrorKind, Result},
+    io::{Error, ErrorKind, Result, SeekFrom},
     net::SocketAddr,
     path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
     sync::{Arc, Mutex, RwLock},
+    time::Duration,
 };
 
 pub struct FileSystemRuntime {
-    handles: Mutex<HashMap<SocketAddr, FileSystemHandle>>,
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    nodes: Mutex<HashMap<SocketAddr, Arc<FileSystem>>>,
     rand: RandomHandle,
     time: TimeHandle,
-    task: TaskHandle,
+    crash_policy: Mutex<CrashPolicy>,
+    fault: Mutex<HashMap<SocketAddr, Arc<FaultState>>>,
+    descriptors: Mutex<HashMap<SocketAddr, DescriptorTable>>,
 }
 
 impl FileSystemRuntime {
-    pub(crate) fn new(rand: RandomHandle, time: TimeHandle, task: TaskHandle) -> Self {
+    pub(crate) fn new(rand: RandomHandle, time: TimeHandle) -> Self {
         FileSystemRuntime {
-            handles: Mutex::new(HashMap::new()),
+            shared: Arc::new(Shared {
+                nodes: Mutex::new(HashMap::new()),
+                rand,
+                time,
+                crash_policy: Mutex::new(CrashPolicy::default()),
+                fault: Mutex::new(HashMap::new()),
+                descriptors: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+
+    pub fn handle(&self) -> FileSystemHandle {
+        FileSystemHandle {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+/// How a power failure decides which unsynced writes survive.
+///
+/// Whatever a policy keeps must be reachable by *some* legal ordering of the
+/// writes the application actually issued, so recovery bugs surface without
+/// ever producing an impossible on-disk state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CrashPolicy {
+    /// Every unsynced write is lost. The strictest, most pessimistic model.
+    #[default]
+    LoseAll,
+    /// Persist a random prefix of the ordered write log, optionally tearing
+    /// the write right at the cut so a single buffered write can land
+    /// partially applied, as if the disk lost power mid-DMA.
+    Prefix,
+    /// Persist a random subsequence of the log, preserving the relative
+    /// order of whatever is kept so overlapping writes can never appear out
+    /// of order.
+    Reorder,
+}
+
+/// Per-node latency and error injection settings.
+///
+/// Everything here is sampled from the runtime's seeded [`RandomHandle`], so a
+/// test that trips an injected error is fully reproducible.
+#[derive(Debug, Clone, Copy)]
+pub struct FaultConfig {
+    /// Uniform range an operation's simulated latency is drawn from.
+    pub latency: (Duration, Duration),
+    /// Probability in `[0, 1]` that an operation fails with a simulated EIO.
+    pub error_rate: f64,
+    /// Total bytes this node's disk can hold before writes start failing with
+    /// a simulated ENOSPC.
+    pub capacity: u64,
+}
+
+impl Default for FaultConfig {
+    fn default() -> Self {
+        FaultConfig {
+            latency: (Duration::ZERO, Duration::ZERO),
+            error_rate: 0.0,
+            capacity: u64::MAX,
+        }
+    }
+}
+
+struct FaultState {
+    config: Mutex<FaultConfig>,
+    used_bytes: AtomicU64,
+    rand: RandomHandle,
+    time: TimeHandle,
+}
+
+impl FaultState {
+    fn new(rand: RandomHandle, time: TimeHandle) -> Self {
+        FaultState {
+            config: Mutex::new(FaultConfig::default()),
+            used_bytes: AtomicU64::new(0),
             rand,
             time,
-            task,
         }
     }
 
-    pub fn handle(&self, addr: SocketAddr) -> FileSystemHandle {
-        let mut handles = self.handles.lock().unwrap();
-        handles
+    async fn delay(&self) {
+        let (min, max) = self.config.lock().unwrap().latency;
+        if max > Duration::ZERO {
+            let delay = self.rand.with(|rng| rng.gen_range(min..=max));
+            self.time.sleep(delay).await;
+        }
+    }
+
+    fn maybe_eio(&self) -> Result<()> {
+        let rate = self.config.lock().unwrap().error_rate;
+        if rate > 0.0 && self.rand.with(|rng| rng.gen_bool(rate)) {
+            return Err(Error::new(ErrorKind::Other, "simulated I/O error (EIO)"));
+        }
+        Ok(())
+    }
+
+    /// Reserve `extra` bytes of disk space, failing with a simulated ENOSPC
+    /// if the node's capacity budget would be exceeded.
+    fn reserve(&self, extra: u64) -> Result<()> {
+        if extra == 0 {
+            return Ok(());
+        }
+        let capacity = self.config.lock().unwrap().capacity;
+        let used = self.used_bytes.fetch_add(extra, Ordering::SeqCst) + extra;
+        if used > capacity {
+            self.used_bytes.fetch_sub(extra, Ordering::SeqCst);
+            return Err(Error::new(
+                ErrorKind::StorageFull,
+                "simulated disk is out of space (ENOSPC)",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Release `amount` bytes previously reserved, e.g. because a truncate
+    /// shrank a file, a file was deleted, or a power failure lost unsynced
+    /// bytes that had been reserved for them. Without this, `used_bytes`
+    /// would only ever grow and a long-running node would eventually hit a
+    /// spurious `StorageFull` that doesn't reflect real usage.
+    fn release(&self, amount: u64) {
+        if amount == 0 {
+            return;
+        }
+        let _ = self
+            .used_bytes
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                Some(used.saturating_sub(amount))
+            });
+    }
+
+    /// Reconcile tracked usage with what a crash actually left durable.
+    /// `believed` is the footprint a path's pending mutations were assumed
+    /// to need (and were reserved/released for, as they were buffered);
+    /// `actual` is what replaying the surviving mutations left on disk. A
+    /// discarded shrink (e.g. a `set_len` that released capacity eagerly,
+    /// then got lost to the crash) can leave `actual` bigger than
+    /// `believed`, in which case the difference needs to be re-accounted
+    /// for rather than released.
+    fn reconcile(&self, believed: u64, actual: u64) {
+        if actual > believed {
+            self.used_bytes.fetch_add(actual - believed, Ordering::SeqCst);
+        } else {
+            self.release(believed - actual);
+        }
+    }
+}
+
+/// A handle to the filesystem simulator, shared by every node.
+#[derive(Clone)]
+pub struct FileSystemHandle {
+    shared: Arc<Shared>,
+}
+
+impl FileSystemHandle {
+    pub fn local_handle(&self, addr: SocketAddr) -> FileSystemLocalHandle {
+        let fault = self.fault_state(addr);
+        let fds = self.descriptor_table(addr);
+        let mut nodes = self.shared.nodes.lock().unwrap();
+        let fs = nodes
             .entry(addr)
-            .or_insert_with(|| Arc::new(FileSystem::new(addr)))
+            .or_insert_with(|| Arc::new(FileSystem::new(addr, self.shared.rand.clone(), fault, fds)))
+            .clone();
+        FileSystemLocalHandle { fs }
+    }
+
+    /// The descriptor table for `addr`. Only `fs::File` registers into it
+    /// today; `net`'s socket types aren't implemented in this tree yet, but
+    /// are meant to register here too once they land, so that
+    /// `Handle::kill`/`power_fail` can close everything a crashed node held
+    /// in one sweep rather than just its open files.
+    pub fn descriptor_table(&self, addr: SocketAddr) -> DescriptorTable {
+        self.shared
+            .descriptors
+            .lock()
+            .unwrap()
+            .entry(addr)
+            .or_insert_with(DescriptorTable::new)
             .clone()
     }
 
-    /// Simulate a power failure. All data that does not reach the disk will be lost.
-    pub fn power_fail(&self, _addr: SocketAddr) {
-        todo!()
+    /// Choose how power failures decide which unsynced writes survive.
+    pub fn set_crash_policy(&self, policy: CrashPolicy) {
+        *self.shared.crash_policy.lock().unwrap() = policy;
+    }
+
+    /// Configure latency and error injection for `addr`.
+    pub fn set_fault_config(&self, addr: SocketAddr, config: FaultConfig) {
+        *self.fault_state(addr).config.lock().unwrap() = config;
+    }
+
+    fn fault_state(&self, addr: SocketAddr) -> Arc<FaultState> {
+        self.shared
+            .fault
+            .lock()
+            .unwrap()
+            .entry(addr)
+            .or_insert_with(|| {
+                Arc::new(FaultState::new(
+                    self.shared.rand.clone(),
+                    self.shared.time.clone(),
+                ))
+            })
+            .clone()
+    }
+
+    /// Simulate a power failure on `addr`, replaying unsynced writes according
+    /// to the configured [`CrashPolicy`].
+    pub fn power_fail(&self, addr: SocketAddr) {
+        trace!("fs({}): power fail", addr);
+        let policy = *self.shared.crash_policy.lock().unwrap();
+        if let Some(fs) = self.shared.nodes.lock().unwrap().get(&addr) {
+            fs.power_fail(policy);
+        }
+        // A crashed node holds none of its descriptors anymore.
+        self.descriptor_table(addr).close_all();
     }
 }
 
-pub type FileSystemHandle = Arc<FileSystem>;
+/// A handle to the filesystem of one node.
+#[derive(Clone)]
+pub struct FileSystemLocalHandle {
+    fs: Arc<FileSystem>,
+}
+
+impl FileSystemLocalHandle {
+    pub async fn open(&self, path: impl AsRef<Path>) -> Result<File> {
+        self.fs.open(path.as_ref()).await
+    }
+
+    pub async fn create(&self, path: impl AsRef<Path>) -> Result<File> {
+        self.fs.create(path.as_ref()).await
+    }
+
+    pub async fn metadata(&self, path: impl AsRef<Path>) -> Result<Metadata> {
+        self.fs.metadata(path.as_ref())
+    }
+
+    pub async fn create_dir(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.fs.create_dir(path.as_ref())
+    }
 
-pub struct FileSystem {
+    pub async fn create_dir_all(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.fs.create_dir_all(path.as_ref())
+    }
+
+    pub async fn read_dir(&self, path: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        self.fs.read_dir(path.as_ref())
+    }
+
+    pub async fn remove_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.fs.remove_file(path.as_ref())
+    }
+
+    pub async fn remove_dir(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.fs.remove_dir(path.as_ref())
+    }
+
+    pub async fn rename(&self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result<()> {
+        self.fs.rename(from.as_ref(), to.as_ref())
+    }
+}
+
+/// One buffered mutation, tagged with the handle that issued it so a reader
+/// can tell its own unsynced writes apart from everyone else's.
+struct Entry {
+    handle: HandleId,
+    mutation: Mutation,
+}
+
+/// A single buffered filesystem mutation. Truncation/extension gets its own
+/// variant, rather than being synthesized as a zero-filled write, so a power
+/// failure can tear a `Write` without ever treating a `set_len` as torn too.
+#[derive(Clone)]
+enum Mutation {
+    Write { offset: u64, data: Vec<u8> },
+    SetLen { size: u64 },
+}
+
+type HandleId = u64;
+
+/// The ordered, not-yet-durable write log for a single path, shared by every
+/// handle that has the path open so a power failure can see and replay all
+/// of it at once.
+type PendingLog = Arc<Mutex<Vec<Entry>>>;
+
+struct FileSystem {
     addr: SocketAddr,
     fs: Mutex<HashMap<PathBuf, Arc<INode>>>,
+    pending: Mutex<HashMap<PathBuf, PendingLog>>,
+    // Pending logs displaced from `pending` by a `remove_file` or a rename
+    // that overwrote an existing destination, kept reachable so a later
+    // `power_fail` can still find and discard their unsynced writes even
+    // though a `File` handle opened before the displacement may still hold
+    // its own clone of the same log. Only the *durable* footprint is
+    // released at displacement time (see `remove_file`); whatever the
+    // dangling handle still has buffered stays reserved exactly like any
+    // other pending write until it is flushed (reserved amount already
+    // matches reality) or a crash discards it (`power_fail` reconciles it
+    // below, reading the inode's current durable length the same way the
+    // main pending loop does, so there's no stale snapshot to go stale).
+    orphaned: Mutex<Vec<(Arc<INode>, PendingLog)>>,
+    next_handle: AtomicU64,
+    rand: RandomHandle,
+    fault: Arc<FaultState>,
+    fds: DescriptorTable,
 }
 
 impl FileSystem {
-    fn new(addr: SocketAddr) -> Self {
+    fn new(
+        addr: SocketAddr,
+        rand: RandomHandle,
+        fault: Arc<FaultState>,
+        fds: DescriptorTable,
+    ) -> Self {
         trace!("fs: new at {}", addr);
+        let mut fs = HashMap::new();
+        // The root directory always exists and has no parent to create.
+        fs.insert(PathBuf::new(), Arc::new(INode::new_dir()));
         FileSystem {
             addr,
-            fs: Mutex::new(HashMap::new()),
+            fs: Mutex::new(fs),
+            pending: Mutex::new(HashMap::new()),
+            orphaned: Mutex::new(Vec::new()),
+            next_handle: AtomicU64::new(0),
+            rand,
+            fault,
+            fds,
         }
     }
 
-    pub async fn open(&self, path: impl AsRef<Path>) -> Result<File> {
-        let path = path.as_ref();
+    fn alloc_handle(&self) -> HandleId {
+        self.next_handle.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn pending_log(&self, path: &Path) -> PendingLog {
+        self.pending
+            .lock()
+            .unwrap()
+            .entry(path.into())
+            .or_insert_with(|| Arc::new(Mutex::new(Vec::new())))
+            .clone()
+    }
+
+    /// Check that `path`'s parent exists and is a directory, like the real OS
+    /// does before creating an entry underneath it.
+    fn check_parent(fs: &HashMap<PathBuf, Arc<INode>>, path: &Path) -> Result<()> {
+        let parent = path.parent().unwrap_or_else(|| Path::new(""));
+        match fs.get(parent) {
+            Some(inode) if inode.is_dir() => Ok(()),
+            Some(_) => Err(Error::new(
+                ErrorKind::Other,
+                format!("not a directory: {:?}", parent),
+            )),
+            None => Err(Error::new(
+                ErrorKind::NotFound,
+                format!("directory not found: {:?}", parent),
+            )),
+        }
+    }
+
+    async fn open(&self, path: &Path) -> Result<File> {
         trace!("fs({}): open at {:?}", self.addr, path);
         let fs = self.fs.lock().unwrap();
         let inode = fs
             .get(path)
-            .ok_or(Error::new(
-                ErrorKind::NotFound,
-                format!("file not found: {:?}", path),
-            ))?
+            .ok_or_else(|| {
+                Error::new(ErrorKind::NotFound, format!("file not found: {:?}", path))
+            })?
             .clone();
+        if inode.is_dir() {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("is a directory: {:?}", path),
+            ));
+        }
+        drop(fs);
         Ok(File {
+            path: path.into(),
             inode,
             can_write: false,
+            cursor: Mutex::new(0),
+            handle: self.alloc_handle(),
+            pending: self.pending_log(path),
+            fault: self.fault.clone(),
+            fd: self.fds.register(),
         })
     }
 
-    pub async fn create(&self, path: impl AsRef<Path>) -> Result<File> {
-        let path = path.as_ref();
+    async fn create(&self, path: &Path) -> Result<File> {
         trace!("fs({}): create at {:?}", self.addr, path);
         let mut fs = self.fs.lock().unwrap();
+        if let Some(inode) = fs.get(path) {
+            if inode.is_dir() {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("is a directory: {:?}", path),
+                ));
+            }
+        } else {
+            Self::check_parent(&fs, path)?;
+        }
         let inode = fs
             .entry(path.into())
-            .or_insert_with(|| Arc::new(INode::new(path)))
+            .or_insert_with(|| Arc::new(INode::new_file(self.fault.time.elapsed())))
             .clone();
+        drop(fs);
         Ok(File {
+            path: path.into(),
             inode,
             can_write: true,
+            cursor: Mutex::new(0),
+            handle: self.alloc_handle(),
+            pending: self.pending_log(path),
+            fault: self.fault.clone(),
+            fd: self.fds.register(),
         })
     }
+
+    fn metadata(&self, path: &Path) -> Result<Metadata> {
+        let fs = self.fs.lock().unwrap();
+        let inode = fs
+            .get(path)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("not found: {:?}", path)))?;
+        Ok(inode.metadata())
+    }
+
+    fn create_dir(&self, path: &Path) -> Result<()> {
+        trace!("fs({}): create_dir at {:?}", self.addr, path);
+        let mut fs = self.fs.lock().unwrap();
+        if fs.contains_key(path) {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("already exists: {:?}", path),
+            ));
+        }
+        Self::check_parent(&fs, path)?;
+        fs.insert(path.into(), Arc::new(INode::new_dir()));
+        Ok(())
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        trace!("fs({}): create_dir_all at {:?}", self.addr, path);
+        let mut fs = self.fs.lock().unwrap();
+        let mut prefix = PathBuf::new();
+        for component in path.components() {
+            prefix.push(component);
+            match fs.get(&prefix) {
+                Some(inode) if inode.is_dir() => {}
+                Some(_) => {
+                    return Err(Error::new(
+                        ErrorKind::Other,
+                        format!("not a directory: {:?}", prefix),
+                    ))
+                }
+                None => {
+                    fs.insert(prefix.clone(), Arc::new(INode::new_dir()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        trace!("fs({}): read_dir at {:?}", self.addr, path);
+        let fs = self.fs.lock().unwrap();
+        match fs.get(path) {
+            Some(inode) if inode.is_dir() => {}
+            Some(_) => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("not a directory: {:?}", path),
+                ))
+            }
+            None => {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("directory not found: {:?}", path),
+                ))
+            }
+        }
+        Ok(fs
+            .keys()
+            .filter(|p| p.parent() == Some(path))
+            .cloned()
+            .collect())
+    }
+
+    fn remove_file(&self, path: &Path) -> Result<()> {
+        trace!("fs({}): remove_file at {:?}", self.addr, path);
+        let mut fs = self.fs.lock().unwrap();
+        let inode = match fs.get(path) {
+            Some(inode) if inode.is_dir() => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("is a directory: {:?}", path),
+                ))
+            }
+            Some(inode) => inode.clone(),
+            None => {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("file not found: {:?}", path),
+                ))
+            }
+        };
+        // Release the durable footprint this file was holding, so deleting
+        // a file actually frees its disk budget. Any still-unsynced growth
+        // buffered by a handle that's still open stays reserved exactly
+        // like an ordinary pending write (see `orphaned` above) — it's
+        // settled by that handle's own later flush or by `power_fail`.
+        let durable_len = inode.file_data().expect("checked above").read().unwrap().len();
+        let removed_log = self.pending.lock().unwrap().remove(path);
+        fs.remove(path);
+        self.fault.release(durable_len as u64);
+        // A `File` handle still open on `path` keeps its own clone of
+        // `removed_log` and can keep pushing unsynced writes into it even
+        // though it's no longer reachable through `self.pending`; track it
+        // so a crash can still lose those writes instead of them silently
+        // surviving because `power_fail` couldn't find the log.
+        if let Some(log) = removed_log {
+            self.orphaned.lock().unwrap().push((inode, log));
+        }
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &Path) -> Result<()> {
+        trace!("fs({}): remove_dir at {:?}", self.addr, path);
+        let mut fs = self.fs.lock().unwrap();
+        match fs.get(path) {
+            Some(inode) if inode.is_dir() => {}
+            Some(_) => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    format!("not a directory: {:?}", path),
+                ))
+            }
+            None => {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("directory not found: {:?}", path),
+                ))
+            }
+        }
+        if fs.keys().any(|p| p.parent() == Some(path)) {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("directory not empty: {:?}", path),
+            ));
+        }
+        fs.remove(path);
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+        trace!("fs({}): rename {:?} -> {:?}", self.addr, from, to);
+        let mut fs = self.fs.lock().unwrap();
+        if !fs.contains_key(from) {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("file not found: {:?}", from),
+            ));
+        }
+        Self::check_parent(&fs, to)?;
+        // Move `from` and, if it is a directory, every entry under it, so a
+        // rename can move a whole subtree in one call.
+        let moved: Vec<PathBuf> = fs
+            .keys()
+            .filter(|p| *p == from || p.starts_with(from))
+            .cloned()
+            .collect();
+        for old_path in moved {
+            let suffix = old_path.strip_prefix(from).unwrap();
+            let new_path = to.join(suffix);
+            // A rename can overwrite an existing entry at the destination;
+            // release whatever capacity it was holding first, the same as
+            // `remove_file` does, so overwriting a file doesn't leak its
+            // footprint. Renaming a path onto itself isn't an overwrite.
+            if new_path != old_path {
+                if let Some(displaced) = fs.remove(&new_path) {
+                    if let Some(data) = displaced.file_data() {
+                        let durable_len = data.read().unwrap().len();
+                        let removed_log = self.pending.lock().unwrap().remove(&new_path);
+                        self.fault.release(durable_len as u64);
+                        if let Some(log) = removed_log {
+                            self.orphaned.lock().unwrap().push((displaced, log));
+                        }
+                    }
+                }
+            }
+            let inode = fs.remove(&old_path).unwrap();
+            fs.insert(new_path.clone(), inode);
+            if let Some(log) = self.pending.lock().unwrap().remove(&old_path) {
+                self.pending.lock().unwrap().insert(new_path, log);
+            }
+        }
+        Ok(())
+    }
+
+    /// Replay every path's unsynced write log according to `policy`, applying
+    /// whatever survives straight into durable storage, then drop the rest.
+    fn power_fail(&self, policy: CrashPolicy) {
+        let fs = self.fs.lock().unwrap();
+        let pending = self.pending.lock().unwrap();
+        for (path, log) in pending.iter() {
+            let Some(inode) = fs.get(path) else {
+                continue;
+            };
+            self.replay_and_reconcile(inode, log, policy);
+        }
+        drop(pending);
+        drop(fs);
+
+        // Paths removed out from under a still-open handle (by `remove_file`
+        // or a renamed-over destination) no longer appear in `self.pending`,
+        // but a handle opened before the removal can still be pushing
+        // unsynced writes into its own clone of that path's log. Those
+        // writes are just as loseable to a crash as any other handle's, and
+        // since only the durable footprint was released at displacement
+        // time (see `remove_file`), whatever this log still has buffered is
+        // reserved exactly like an ordinary pending write — so it's
+        // reconciled the exact same way, against the inode's *current*
+        // durable length rather than a snapshot taken at orphan time, which
+        // would go stale across an intervening flush.
+        for (inode, log) in self.orphaned.lock().unwrap().drain(..) {
+            self.replay_and_reconcile(&inode, &log, policy);
+        }
+    }
+
+    /// Replay one path's unsynced log against `policy` and square up the
+    /// capacity it was reserved for against what actually survives, shared
+    /// by both the regular and orphaned-log passes of [`Self::power_fail`].
+    fn replay_and_reconcile(&self, inode: &Arc<INode>, log: &PendingLog, policy: CrashPolicy) {
+        let entries = std::mem::take(&mut *log.lock().unwrap());
+        let Some(data) = inode.file_data() else {
+            return;
+        };
+        let mut data = data.write().unwrap();
+        // `before` is the footprint these mutations were reserved (or
+        // released) for as they were buffered; `reconcile` squares that up
+        // against what actually survives the replay, in either direction
+        // (e.g. a `set_len` shrink can release capacity eagerly and then
+        // get discarded here, leaving more durable data than `before`
+        // accounted for).
+        let before = mutations_footprint(data.len(), entries.iter().map(|e| &e.mutation));
+        for mutation in self.replay(policy, entries) {
+            apply_mutation(&mut data, &mutation);
+        }
+        self.fault.reconcile(before as u64, data.len() as u64);
+    }
+
+    fn replay(&self, policy: CrashPolicy, entries: Vec<Entry>) -> Vec<Mutation> {
+        match policy {
+            CrashPolicy::LoseAll => Vec::new(),
+            CrashPolicy::Prefix => {
+                let keep = self.rand.with(|rng| rng.gen_range(0..=entries.len()));
+                let mut survivors: Vec<Mutation> =
+                    entries[..keep].iter().map(|e| e.mutation.clone()).collect();
+                // Optionally tear the write right at the cut, as if the disk
+                // only got through part of it before power was lost. A
+                // truncate/extend is atomic from the simulator's point of
+                // view, so only a `Write` can be torn.
+                if let Some(Entry {
+                    mutation: Mutation::Write { offset, data },
+                    ..
+                }) = entries.get(keep)
+                {
+                    if !data.is_empty() && self.rand.with(|rng| rng.gen_bool(0.5)) {
+                        let split = self.rand.with(|rng| rng.gen_range(1..=data.len()));
+                        survivors.push(Mutation::Write {
+                            offset: *offset,
+                            data: data[..split].to_vec(),
+                        });
+                    }
+                }
+                survivors
+            }
+            CrashPolicy::Reorder => entries
+                .into_iter()
+                .filter(|_| self.rand.with(|rng| rng.gen_bool(0.5)))
+                .map(|e| e.mutation)
+                .collect(),
+        }
+    }
 }
 
-struct INode {
-    path: PathBuf,
-    data: RwLock<Vec<u8>>,
+/// A file's size and simulated modification time.
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata {
+    len: u64,
+    modified: Duration,
+    is_dir: bool,
+}
+
+impl Metadata {
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Time this entry was last modified, relative to the runtime's clock.
+    pub fn modified(&self) -> Duration {
+        self.modified
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    pub fn is_file(&self) -> bool {
+        !self.is_dir
+    }
+}
+
+enum INode {
+    File {
+        data: RwLock<Vec<u8>>,
+        mtime: Mutex<Duration>,
+    },
+    Dir,
 }
 
 impl INode {
-    fn new(path: &Path) -> Self {
-        INode {
-            path: path.into(),
+    fn new_file(now: Duration) -> Self {
+        INode::File {
             data: RwLock::new(Vec::new()),
+            mtime: Mutex::new(now),
+        }
+    }
+
+    fn new_dir() -> Self {
+        INode::Dir
+    }
+
+    fn is_dir(&self) -> bool {
+        matches!(self, INode::Dir)
+    }
+
+    fn file_data(&self) -> Option<&RwLock<Vec<u8>>> {
+        match self {
+            INode::File { data, .. } => Some(data),
+            INode::Dir => None,
+        }
+    }
+
+    fn touch(&self, now: Duration) {
+        if let INode::File { mtime, .. } = self {
+            *mtime.lock().unwrap() = now;
+        }
+    }
+
+    fn metadata(&self) -> Metadata {
+        match self {
+            INode::File { data, mtime } => Metadata {
+                len: data.read().unwrap().len() as u64,
+                modified: *mtime.lock().unwrap(),
+                is_dir: false,
+            },
+            INode::Dir => Metadata {
+                len: 0,
+                modified: Duration::ZERO,
+                is_dir: true,
+            },
         }
     }
 }
 
 pub struct File {
+    path: PathBuf,
     inode: Arc<INode>,
     can_write: bool,
+    /// Cursor for the sequential `read`/`write`/`seek` API.
+    cursor: Mutex<u64>,
+    handle: HandleId,
+    pending: PendingLog,
+    fault: Arc<FaultState>,
+    fd: OwnedSimFd,
+}
+
+impl AsSimFd for File {
+    fn as_sim_fd(&self) -> BorrowedSimFd<'_> {
+        self.fd.as_borrowed()
+    }
 }
 
 impl File {
+    fn check_open(&self) -> Result<()> {
+        if self.fd.is_open() {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::Other,
+                "use of a descriptor closed by a simulated crash",
+            ))
+        }
+    }
+
     pub async fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
         trace!(
             "file({:?}): read_at: offset={}, len={}",
-            self.inode.path,
+            self.path,
             offset,
             buf.len()
         );
-        let data = self.inode.data.read().unwrap();
-        let end = data.len().min(offset as usize + buf.len());
-        let len = end - offset as usize;
-        buf[..len].copy_from_slice(&data[offset as usize..end]);
-        // TODO: random delay
+        self.check_open()?;
+        self.fault.delay().await;
+        self.fault.maybe_eio()?;
+        // A read observes durable data plus this handle's own unflushed writes,
+        // but never another handle's pending writes.
+        let mut view = self.data().read().unwrap().clone();
+        for entry in self.pending.lock().unwrap().iter() {
+            if entry.handle == self.handle {
+                apply_mutation(&mut view, &entry.mutation);
+            }
+        }
+        let start = offset as usize;
+        if start >= view.len() {
+            return Ok(0);
+        }
+        let end = view.len().min(start + buf.len());
+        let len = end - start;
+        buf[..len].copy_from_slice(&view[start..end]);
         Ok(len)
     }
 
     pub async fn write_all_at(&self, buf: &[u8], offset: u64) -> Result<()> {
         trace!(
             "file({:?}): write_all_at: offset={}, len={}",
-            self.inode.path,
+            self.path,
             offset,
             buf.len()
         );
@@ -135,39 +866,179 @@ impl File {
                 "the file is read only",
             ));
         }
-        let mut data = self.inode.data.write().unwrap();
-        let end = data.len().min(offset as usize + buf.len());
-        let len = end - offset as usize;
-        data[offset as usize..end].copy_from_slice(&buf[..len]);
-        if len < buf.len() {
-            data.extend_from_slice(&buf[len..]);
-        }
-        // TODO: random delay
-        // TODO: simulate buffer, write will not take effect until flush or close
+        self.check_open()?;
+        self.fault.delay().await;
+        self.fault.maybe_eio()?;
+        let extra = (offset as usize + buf.len()).saturating_sub(self.apparent_len()) as u64;
+        self.fault.reserve(extra)?;
+        self.pending.lock().unwrap().push(Entry {
+            handle: self.handle,
+            mutation: Mutation::Write {
+                offset,
+                data: buf.to_vec(),
+            },
+        });
+        self.inode.touch(self.fault.time.elapsed());
         Ok(())
     }
 
     pub async fn set_len(&self, size: u64) -> Result<()> {
-        trace!("file({:?}): set_len={}", self.inode.path, size,);
-        let mut data = self.inode.data.write().unwrap();
-        data.resize(size as usize, 0);
+        trace!("file({:?}): set_len={}", self.path, size);
+        self.check_open()?;
+        self.fault.delay().await;
+        self.fault.maybe_eio()?;
+        let current = self.apparent_len() as u64;
+        if size > current {
+            self.fault.reserve(size - current)?;
+        } else if size < current {
+            self.fault.release(current - size);
+        }
+        // Like `write_all_at`, a truncate/extend is buffered until the next
+        // flush/sync instead of landing on durable storage immediately, so
+        // it is just as loseable to a crash as any other unsynced write.
+        self.pending.lock().unwrap().push(Entry {
+            handle: self.handle,
+            mutation: Mutation::SetLen { size },
+        });
+        self.inode.touch(self.fault.time.elapsed());
         Ok(())
     }
+
+    pub async fn metadata(&self) -> Result<Metadata> {
+        // Mirror read_at's rule: this handle observes its own unflushed
+        // writes, so its view of the length must too.
+        let mut meta = self.inode.metadata();
+        meta.len = self.apparent_len() as u64;
+        Ok(meta)
+    }
+
+    /// Read from, and advance, this handle's sequential cursor.
+    pub async fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        let offset = *self.cursor.lock().unwrap();
+        let len = self.read_at(buf, offset).await?;
+        *self.cursor.lock().unwrap() = offset + len as u64;
+        Ok(len)
+    }
+
+    /// Write at, and advance, this handle's sequential cursor.
+    pub async fn write(&self, buf: &[u8]) -> Result<()> {
+        let offset = *self.cursor.lock().unwrap();
+        self.write_all_at(buf, offset).await?;
+        *self.cursor.lock().unwrap() = offset + buf.len() as u64;
+        Ok(())
+    }
+
+    pub async fn seek(&self, pos: SeekFrom) -> Result<u64> {
+        let new = match pos {
+            SeekFrom::Start(n) => n as i64,
+            SeekFrom::End(n) => self.apparent_len() as i64 + n,
+            SeekFrom::Current(n) => *self.cursor.lock().unwrap() as i64 + n,
+        };
+        let new = u64::try_from(new)
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "seek position out of range"))?;
+        *self.cursor.lock().unwrap() = new;
+        Ok(new)
+    }
+
+    fn data(&self) -> &RwLock<Vec<u8>> {
+        self.inode.file_data().expect("a File never wraps a Dir")
+    }
+
+    /// The file's size as seen through this handle: durable data plus
+    /// whatever is still buffered in the pending write log.
+    fn apparent_len(&self) -> usize {
+        let durable = self.data().read().unwrap().len();
+        mutations_footprint(
+            durable,
+            self.pending
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|e| e.handle == self.handle)
+                .map(|e| &e.mutation),
+        )
+    }
+
+    /// Flush this handle's pending writes into durable storage (fsync).
+    pub async fn sync_all(&self) -> Result<()> {
+        self.flush_locked();
+        Ok(())
+    }
+
+    /// Flush this handle's pending writes into durable storage, without also
+    /// syncing metadata. Equivalent to [`File::sync_all`] in this simulator.
+    pub async fn sync_data(&self) -> Result<()> {
+        self.flush_locked();
+        Ok(())
+    }
+
+    /// Drain this handle's share of the pending write log into the durable
+    /// inode data.
+    pub async fn flush(&self) -> Result<()> {
+        self.flush_locked();
+        Ok(())
+    }
+
+    fn flush_locked(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        if !pending.iter().any(|e| e.handle == self.handle) {
+            return;
+        }
+        let mut data = self.data().write().unwrap();
+        for entry in pending.iter().filter(|e| e.handle == self.handle) {
+            apply_mutation(&mut data, &entry.mutation);
+        }
+        pending.retain(|e| e.handle != self.handle);
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        self.flush_locked();
+    }
+}
+
+fn apply_write(data: &mut Vec<u8>, offset: u64, bytes: &[u8]) {
+    let offset = offset as usize;
+    let end = offset + bytes.len();
+    if data.len() < end {
+        data.resize(end, 0);
+    }
+    data[offset..end].copy_from_slice(bytes);
+}
+
+fn apply_mutation(data: &mut Vec<u8>, mutation: &Mutation) {
+    match mutation {
+        Mutation::Write { offset, data: bytes } => apply_write(data, *offset, bytes),
+        Mutation::SetLen { size } => data.resize(*size as usize, 0),
+    }
+}
+
+/// The extent implied by applying `mutations`, in order, on top of a buffer
+/// that currently has length `len`.
+fn mutations_footprint<'a>(mut len: usize, mutations: impl Iterator<Item = &'a Mutation>) -> usize {
+    for mutation in mutations {
+        match mutation {
+            Mutation::Write { offset, data } => len = len.max(*offset as usize + data.len()),
+            Mutation::SetLen { size } => len = *size as usize,
+        }
+    }
+    len
 }
 
 #[cfg(test)]
 mod tests {
+    use super::CrashPolicy;
     use crate::Runtime;
-    use std::io::ErrorKind;
+    use std::io::{ErrorKind, SeekFrom};
 
     #[test]
     fn create_open_read_write() {
-        crate::init_logger();
-
-        let runtime = Runtime::new().unwrap();
-        let host = runtime.handle("0.0.0.1:1".parse().unwrap());
-        let fs = host.fs().clone();
-        let f = host.spawn(async move {
+        let runtime = Runtime::new();
+        let addr = "0.0.0.1:1".parse().unwrap();
+        let local = runtime.local_handle(addr);
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
             assert_eq!(
                 fs.open("file").await.err().unwrap().kind(),
                 ErrorKind::NotFound
@@ -175,10 +1046,13 @@ mod tests {
             let file = fs.create("file").await.unwrap();
             file.write_all_at(b"hello", 0).await.unwrap();
 
+            // the writing handle sees its own unflushed write immediately
             let mut buf = [0u8; 10];
             let read_len = file.read_at(&mut buf, 2).await.unwrap();
             assert_eq!(read_len, 3);
             assert_eq!(&buf[..3], b"llo");
+
+            file.sync_all().await.unwrap();
             drop(file);
 
             let rofile = fs.open("file").await.unwrap();
@@ -186,7 +1060,515 @@ mod tests {
                 rofile.write_all_at(b"gg", 0).await.err().unwrap().kind(),
                 ErrorKind::PermissionDenied
             );
+            let mut buf = [0u8; 5];
+            rofile.read_at(&mut buf, 0).await.unwrap();
+            assert_eq!(&buf, b"hello");
+        });
+        runtime.block_on(f);
+    }
+
+    #[test]
+    fn metadata_and_seek_only_see_this_handles_own_pending_writes() {
+        let runtime = Runtime::new();
+        let addr = "0.0.0.1:1".parse().unwrap();
+        let local = runtime.local_handle(addr);
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
+            let writer = fs.create("file").await.unwrap();
+            writer.write_all_at(b"hello", 0).await.unwrap();
+
+            // the writer observes its own unflushed extension...
+            assert_eq!(writer.metadata().await.unwrap().len(), 5);
+            assert_eq!(writer.seek(SeekFrom::End(0)).await.unwrap(), 5);
+
+            // ...but a second, unrelated handle on the same path must not,
+            // until the writer actually flushes.
+            let reader = fs.open("file").await.unwrap();
+            assert_eq!(reader.metadata().await.unwrap().len(), 0);
+            assert_eq!(reader.seek(SeekFrom::End(0)).await.unwrap(), 0);
+
+            writer.sync_all().await.unwrap();
+            assert_eq!(reader.metadata().await.unwrap().len(), 5);
+        });
+        runtime.block_on(f);
+    }
+
+    #[test]
+    fn power_fail_drops_unsynced_writes() {
+        let runtime = Runtime::new();
+        let addr = "0.0.0.1:1".parse().unwrap();
+        let local = runtime.local_handle(addr);
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
+            let file = fs.create("file").await.unwrap();
+            file.write_all_at(b"hello", 0).await.unwrap();
+            file.sync_all().await.unwrap();
+            file.write_all_at(b"world", 5).await.unwrap();
+            // "world" is still only in the write-back cache when power fails.
+            crate::Handle::current().fs.power_fail(addr);
+
+            let file = fs.open("file").await.unwrap();
+            let mut buf = [0u8; 10];
+            let read_len = file.read_at(&mut buf, 0).await.unwrap();
+            assert_eq!(&buf[..read_len], b"hello");
+        });
+        runtime.block_on(f);
+    }
+
+    #[test]
+    fn kill_loses_unsynced_writes_even_from_a_file_still_open_when_it_fires() {
+        let runtime = Runtime::new();
+        let addr = "0.0.0.1:1".parse().unwrap();
+        let local = runtime.local_handle(addr);
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
+            let file = fs.create("file").await.unwrap();
+            file.write_all_at(b"hello", 0).await.unwrap();
+            file.sync_all().await.unwrap();
+            file.write_all_at(b"WORLD", 0).await.unwrap();
+
+            // `task.rs` doesn't exist in this tree, so there's no real
+            // executor to abort a task and drop `file` out from under it.
+            // What `Handle::kill`'s ordering guarantees, though, is that by
+            // the time it returns, `fs.power_fail` has already drained
+            // "file"'s pending log — so a `File::drop` that fires any time
+            // after `kill`, exactly as an aborted task's would, finds
+            // nothing left of the unsynced write to flush.
+            crate::Handle::current().kill(addr);
+            drop(file);
+
+            let fs = crate::Handle::current().fs.local_handle(addr);
+            let file = fs.open("file").await.unwrap();
+            let mut buf = [0u8; 10];
+            let len = file.read_at(&mut buf, 0).await.unwrap();
+            assert_eq!(&buf[..len], b"hello");
+        });
+        runtime.block_on(f);
+    }
+
+    #[test]
+    fn crash_policy_prefix_only_keeps_a_legal_prefix() {
+        let runtime = Runtime::new();
+        let addr = "0.0.0.1:1".parse().unwrap();
+        let local = runtime.local_handle(addr);
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
+            crate::Handle::current().fs.set_crash_policy(CrashPolicy::Prefix);
+            let file = fs.create("file").await.unwrap();
+            file.write_all_at(b"AAAA", 0).await.unwrap();
+            file.write_all_at(b"BBBB", 4).await.unwrap();
+            crate::Handle::current().fs.power_fail(addr);
+
+            // whatever survived must be a legal prefix: either nothing, a
+            // torn "AAAA", a whole "AAAA", or a torn/whole "AAAABBBB".
+            let file = fs.open("file").await.unwrap();
+            let mut buf = [0u8; 8];
+            let len = file.read_at(&mut buf, 0).await.unwrap();
+            let survived = &buf[..len];
+            assert!(b"AAAABBBB".starts_with(survived));
+        });
+        runtime.block_on(f);
+    }
+
+    #[test]
+    fn fault_injection_is_deterministic() {
+        use super::FaultConfig;
+
+        let runtime = Runtime::new();
+        let addr = "0.0.0.1:1".parse().unwrap();
+        let local = runtime.local_handle(addr);
+        runtime.handle().fs.set_fault_config(
+            addr,
+            FaultConfig {
+                error_rate: 1.0,
+                ..FaultConfig::default()
+            },
+        );
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
+            let file = fs.create("file").await.unwrap();
+            assert_eq!(
+                file.write_all_at(b"hello", 0).await.err().unwrap().kind(),
+                ErrorKind::Other
+            );
+        });
+        runtime.block_on(f);
+    }
+
+    #[test]
+    fn out_of_space_is_reported_as_storage_full() {
+        use super::FaultConfig;
+
+        let runtime = Runtime::new();
+        let addr = "0.0.0.1:1".parse().unwrap();
+        let local = runtime.local_handle(addr);
+        runtime.handle().fs.set_fault_config(
+            addr,
+            FaultConfig {
+                capacity: 4,
+                ..FaultConfig::default()
+            },
+        );
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
+            let file = fs.create("file").await.unwrap();
+            assert_eq!(
+                file.write_all_at(b"hello", 0).await.err().unwrap().kind(),
+                ErrorKind::StorageFull
+            );
+        });
+        runtime.block_on(f);
+    }
+
+    #[test]
+    fn deleting_and_truncating_frees_capacity() {
+        use super::FaultConfig;
+
+        let runtime = Runtime::new();
+        let addr = "0.0.0.1:1".parse().unwrap();
+        let local = runtime.local_handle(addr);
+        runtime.handle().fs.set_fault_config(
+            addr,
+            FaultConfig {
+                capacity: 4,
+                ..FaultConfig::default()
+            },
+        );
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
+
+            // fill the budget, then truncate it back to empty...
+            let file = fs.create("a").await.unwrap();
+            file.write_all_at(b"aaaa", 0).await.unwrap();
+            file.set_len(0).await.unwrap();
+            drop(file);
+
+            // ...and the freed capacity should be usable by another file.
+            let file = fs.create("b").await.unwrap();
+            file.write_all_at(b"bbbb", 0).await.unwrap();
+            drop(file);
+
+            // deleting "b" should free its capacity too.
+            fs.remove_file("b").await.unwrap();
+            let file = fs.create("c").await.unwrap();
+            file.write_all_at(b"cccc", 0).await.unwrap();
+        });
+        runtime.block_on(f);
+    }
+
+    #[test]
+    fn crash_discarding_an_unsynced_shrink_reclaims_its_eagerly_released_capacity() {
+        use super::FaultConfig;
+
+        let runtime = Runtime::new();
+        let addr = "0.0.0.1:1".parse().unwrap();
+        let local = runtime.local_handle(addr);
+        runtime.handle().fs.set_fault_config(
+            addr,
+            FaultConfig {
+                capacity: 4,
+                ..FaultConfig::default()
+            },
+        );
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
+
+            let file = fs.create("a").await.unwrap();
+            file.write_all_at(b"aaaa", 0).await.unwrap();
+            file.sync_all().await.unwrap();
+            file.write_all_at(b"bbbb", 0).await.unwrap();
+            // `set_len` releases a shrink's capacity as soon as it's
+            // buffered, before it's durable. A crash then discards both
+            // unsynced mutations, leaving "aaaa" on disk — so the capacity
+            // it still occupies must be reconciled back in, not left
+            // released.
+            file.set_len(0).await.unwrap();
+            crate::Handle::current().fs.power_fail(addr);
+
+            let file = fs.open("a").await.unwrap();
+            let mut buf = [0u8; 4];
+            let len = file.read_at(&mut buf, 0).await.unwrap();
+            assert_eq!(&buf[..len], b"aaaa");
+
+            // "aaaa" still occupies the whole 4-byte budget, so creating
+            // another file with any data must fail with ENOSPC.
+            let other = fs.create("c").await.unwrap();
+            assert_eq!(
+                other.write_all_at(b"c", 0).await.err().unwrap().kind(),
+                ErrorKind::StorageFull
+            );
+        });
+        runtime.block_on(f);
+    }
+
+    #[test]
+    fn directories_and_rename() {
+        let runtime = Runtime::new();
+        let addr = "0.0.0.1:1".parse().unwrap();
+        let local = runtime.local_handle(addr);
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
+            assert_eq!(
+                fs.create("a/file").await.err().unwrap().kind(),
+                ErrorKind::NotFound
+            );
+            fs.create_dir_all("a/b").await.unwrap();
+            let file = fs.create("a/b/file").await.unwrap();
+            drop(file);
+
+            let entries = fs.read_dir("a/b").await.unwrap();
+            assert_eq!(entries, vec![std::path::PathBuf::from("a/b/file")]);
+
+            fs.rename("a/b", "a/c").await.unwrap();
+            assert_eq!(
+                fs.open("a/b/file").await.err().unwrap().kind(),
+                ErrorKind::NotFound
+            );
+            let meta = fs.metadata("a/c/file").await.unwrap();
+            assert_eq!(meta.len(), 0);
+            assert!(meta.is_file());
+
+            fs.remove_file("a/c/file").await.unwrap();
+            assert_eq!(
+                fs.remove_dir("a").await.err().unwrap().kind(),
+                ErrorKind::Other
+            );
+            fs.remove_dir("a/c").await.unwrap();
+            fs.remove_dir("a").await.unwrap();
+        });
+        runtime.block_on(f);
+    }
+
+    #[test]
+    fn renaming_a_path_onto_itself_is_a_no_op() {
+        let runtime = Runtime::new();
+        let addr = "0.0.0.1:1".parse().unwrap();
+        let local = runtime.local_handle(addr);
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
+            let file = fs.create("a").await.unwrap();
+            file.write_all_at(b"hello", 0).await.unwrap();
+            file.sync_all().await.unwrap();
+            drop(file);
+
+            fs.rename("a", "a").await.unwrap();
+            let file = fs.open("a").await.unwrap();
+            let mut buf = [0u8; 5];
+            let len = file.read_at(&mut buf, 0).await.unwrap();
+            assert_eq!(&buf[..len], b"hello");
+        });
+        runtime.block_on(f);
+    }
+
+    #[test]
+    fn renaming_over_an_existing_file_frees_its_capacity() {
+        use super::FaultConfig;
+
+        let runtime = Runtime::new();
+        let addr = "0.0.0.1:1".parse().unwrap();
+        let local = runtime.local_handle(addr);
+        runtime.handle().fs.set_fault_config(
+            addr,
+            FaultConfig {
+                capacity: 8,
+                ..FaultConfig::default()
+            },
+        );
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
+
+            let from = fs.create("from").await.unwrap();
+            from.write_all_at(b"from", 0).await.unwrap();
+            from.sync_all().await.unwrap();
+            drop(from);
+
+            let to = fs.create("to").await.unwrap();
+            to.write_all_at(b"to!!", 0).await.unwrap();
+            to.sync_all().await.unwrap();
+            drop(to);
+
+            // "from" and "to" together already fill the 8-byte budget, so
+            // without releasing "to"'s displaced footprint the rename
+            // below would leave no room for anything else.
+            fs.rename("from", "to").await.unwrap();
+            let file = fs.open("to").await.unwrap();
+            let mut buf = [0u8; 4];
+            let len = file.read_at(&mut buf, 0).await.unwrap();
+            assert_eq!(&buf[..len], b"from");
+
+            let other = fs.create("other").await.unwrap();
+            other.write_all_at(b"ok!!", 0).await.unwrap();
+        });
+        runtime.block_on(f);
+    }
+
+    #[test]
+    fn crash_discarding_an_orphaned_handles_growth_reconciles_its_capacity() {
+        use super::FaultConfig;
+
+        let runtime = Runtime::new();
+        let addr = "0.0.0.1:1".parse().unwrap();
+        let local = runtime.local_handle(addr);
+        runtime.handle().fs.set_fault_config(
+            addr,
+            FaultConfig {
+                capacity: 4,
+                ..FaultConfig::default()
+            },
+        );
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
+
+            let file = fs.create("file").await.unwrap();
+            file.write_all_at(b"aaaa", 0).await.unwrap();
+            file.sync_all().await.unwrap();
+
+            // removing "file" while it's still open releases its 4-byte
+            // footprint (the delete-frees-capacity simplification), but
+            // the still-open handle can keep extending it further.
+            fs.remove_file("file").await.unwrap();
+            file.write_all_at(b"bbbb", 4).await.unwrap();
+
+            // the crash discards that unsynced extension, so the growth it
+            // was reserved for must be given back too, not just the
+            // footprint already released at removal time.
+            crate::Handle::current().fs.power_fail(addr);
+
+            let other = fs.create("other").await.unwrap();
+            other.write_all_at(b"ok!!", 0).await.unwrap();
+        });
+        runtime.block_on(f);
+    }
+
+    #[test]
+    fn power_fail_drains_the_orphaned_log_of_a_handle_open_on_a_removed_path() {
+        let runtime = Runtime::new();
+        let addr = "0.0.0.1:1".parse().unwrap();
+        let local = runtime.local_handle(addr);
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
+
+            let file = fs.create("file").await.unwrap();
+            file.write_all_at(b"hello", 0).await.unwrap();
+            file.sync_all().await.unwrap();
+
+            // unlinking "file" while `file` is still open orphans its
+            // pending log: `file` keeps its own clone and can still push
+            // unsynced writes into it, even though the path is gone from
+            // `fs`. A crash must be able to find and drain that log too,
+            // instead of it being unreachable forever.
+            fs.remove_file("file").await.unwrap();
+            file.write_all_at(b"WORLD", 0).await.unwrap();
+            assert_eq!(fs.fs.orphaned.lock().unwrap().len(), 1);
+
+            crate::Handle::current().fs.power_fail(addr);
+            assert_eq!(fs.fs.orphaned.lock().unwrap().len(), 0);
+
+            // a crash closes every descriptor, including ones left open on
+            // an already-removed path.
+            assert_eq!(
+                file.read_at(&mut [0u8; 5], 0).await.err().unwrap().kind(),
+                ErrorKind::Other
+            );
+        });
+        runtime.block_on(f);
+    }
+
+    #[test]
+    fn seek_based_sequential_io() {
+        let runtime = Runtime::new();
+        let addr = "0.0.0.1:1".parse().unwrap();
+        let local = runtime.local_handle(addr);
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
+            let file = fs.create("file").await.unwrap();
+            file.write(b"hello").await.unwrap();
+            file.write(b" world").await.unwrap();
+            file.seek(SeekFrom::Start(0)).await.unwrap();
+            let mut buf = [0u8; 11];
+            let len = file.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..len], b"hello world");
+        });
+        runtime.block_on(f);
+    }
+
+    #[test]
+    fn set_len_is_buffered_like_a_write() {
+        let runtime = Runtime::new();
+        let addr = "0.0.0.1:1".parse().unwrap();
+        let local = runtime.local_handle(addr);
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
+            let file = fs.create("file").await.unwrap();
+            file.write_all_at(b"hello", 0).await.unwrap();
+            file.sync_all().await.unwrap();
+
+            // the truncating handle sees its own unsynced set_len immediately
+            file.set_len(2).await.unwrap();
+            let mut buf = [0u8; 5];
+            let len = file.read_at(&mut buf, 0).await.unwrap();
+            assert_eq!(&buf[..len], b"he");
+
+            // ...but an unsynced set_len is just as loseable as any other
+            // unsynced write.
+            crate::Handle::current().fs.power_fail(addr);
+            assert_eq!(
+                file.set_len(0).await.err().unwrap().kind(),
+                ErrorKind::Other
+            );
+
+            let file = fs.open("file").await.unwrap();
+            let mut buf = [0u8; 5];
+            let len = file.read_at(&mut buf, 0).await.unwrap();
+            assert_eq!(&buf[..len], b"hello");
+        });
+        runtime.block_on(f);
+    }
+
+    #[test]
+    fn power_fail_closes_every_descriptor() {
+        use crate::fd::AsSimFd;
+
+        let runtime = Runtime::new();
+        let addr = "0.0.0.1:1".parse().unwrap();
+        let local = runtime.local_handle(addr);
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
+            let file = fs.create("file").await.unwrap();
+            assert!(file.as_sim_fd().is_open());
+
+            crate::Handle::current().fs.power_fail(addr);
+            assert!(!file.as_sim_fd().is_open());
+            assert_eq!(
+                file.read_at(&mut [0u8; 1], 0).await.err().unwrap().kind(),
+                ErrorKind::Other
+            );
+        });
+        runtime.block_on(f);
+    }
+
+    #[test]
+    fn crash_and_restart_keeps_synced_data() {
+        let runtime = Runtime::new();
+        let addr = "0.0.0.1:1".parse().unwrap();
+        let local = runtime.local_handle(addr);
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
+            let file = fs.create("file").await.unwrap();
+            file.write_all_at(b"hello", 0).await.unwrap();
+            file.sync_all().await.unwrap();
+        });
+        runtime.block_on(f);
+
+        runtime.handle().kill(addr);
+        let local = runtime.restart_node(addr);
+        let f = local.spawn(async move {
+            let fs = crate::Handle::current().fs.local_handle(addr);
+            let file = fs.open("file").await.unwrap();
+            let mut buf = [0u8; 5];
+            file.read_at(&mut buf, 0).await.unwrap();
+            assert_eq!(&buf, b"hello");
         });
-        runtime.block_on(f).unwrap();
+        runtime.block_on(f);
     }
 }